@@ -0,0 +1,133 @@
+//! Clipboard backends.
+//!
+//! `copy_to_clipboard` tries an in-process clipboard crate first (works
+//! headlessly wherever it's supported), then falls back to shelling out to
+//! whichever platform tool is actually present: `wl-copy` under Wayland,
+//! `xclip`/`xsel` under X11, or the native macOS/Windows tool. The backend
+//! can be forced with `--clipboard <backend>` or `$CBSUB_CLIPBOARD`.
+
+use std::env;
+use std::error::Error;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const BACKEND_NAMES: &[&str] = &["arboard", "wl-copy", "xclip", "xsel", "pbcopy", "clip"];
+
+/// Copies `text` to the system clipboard.
+///
+/// If `forced_backend` is `Some` (or, failing that, `$CBSUB_CLIPBOARD` is
+/// set), only that backend is tried (an unknown name or a failing backend is
+/// an error). Otherwise backends are tried in
+/// order for the current platform, and the error lists everything attempted.
+pub fn copy_to_clipboard(text: &str, forced_backend: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let forced = forced_backend.map(|s| s.to_string()).or_else(|| env::var("CBSUB_CLIPBOARD").ok());
+    if let Some(name) = forced {
+        return copy_with_backend(&name, text);
+    }
+
+    let mut attempted = Vec::new();
+    for name in candidate_backends() {
+        attempted.push(name);
+        if copy_with_backend(name, text).is_ok() {
+            return Ok(());
+        }
+    }
+
+    Err(format!(
+        "Error: Could not copy to clipboard. Tried: {}. Install one of these, or set $CBSUB_CLIPBOARD / pass --clipboard explicitly.",
+        attempted.join(", ")
+    )
+    .into())
+}
+
+/// Backends worth trying, in order, for the current platform.
+fn candidate_backends() -> Vec<&'static str> {
+    let mut backends = vec!["arboard"];
+    if cfg!(target_os = "macos") {
+        backends.push("pbcopy");
+    } else if cfg!(target_os = "windows") {
+        backends.push("clip");
+    } else {
+        if env::var("WAYLAND_DISPLAY").is_ok() {
+            backends.push("wl-copy");
+        }
+        backends.push("xclip");
+        backends.push("xsel");
+    }
+    backends
+}
+
+fn copy_with_backend(name: &str, text: &str) -> Result<(), Box<dyn Error>> {
+    match name {
+        "arboard" => copy_with_arboard(text),
+        "wl-copy" => copy_with_command("wl-copy", &[], text),
+        "xclip" => copy_with_command("xclip", &["-selection", "clipboard"], text),
+        "xsel" => copy_with_command("xsel", &["--clipboard", "--input"], text),
+        "pbcopy" => copy_with_command("pbcopy", &[], text),
+        "clip" => copy_with_command("clip", &[], text),
+        other => Err(format!(
+            "Unknown clipboard backend '{}'. Known backends: {}.",
+            other,
+            BACKEND_NAMES.join(", ")
+        )
+        .into()),
+    }
+}
+
+fn copy_with_arboard(text: &str) -> Result<(), Box<dyn Error>> {
+    let mut clipboard = arboard::Clipboard::new()?;
+    clipboard.set_text(text.to_string())?;
+    Ok(())
+}
+
+fn copy_with_command(program: &str, args: &[&str], text: &str) -> Result<(), Box<dyn Error>> {
+    let mut process = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    process.stdin.as_mut().ok_or("Failed to open clipboard process stdin")?.write_all(text.as_bytes())?;
+    drop(process.stdin.take());
+
+    let output = process.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("'{}' exited with {}: {}", program, output.status, stderr.trim()).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidate_backends_tries_arboard_first() {
+        assert_eq!(candidate_backends().first(), Some(&"arboard"));
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    #[test]
+    fn test_candidate_backends_falls_back_to_x11_tools() {
+        env::remove_var("WAYLAND_DISPLAY");
+        let backends = candidate_backends();
+        assert!(backends.contains(&"xclip"));
+        assert!(backends.contains(&"xsel"));
+        assert!(!backends.contains(&"wl-copy"));
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    #[test]
+    fn test_candidate_backends_tries_wl_copy_under_wayland() {
+        env::set_var("WAYLAND_DISPLAY", "wayland-0");
+        assert!(candidate_backends().contains(&"wl-copy"));
+        env::remove_var("WAYLAND_DISPLAY");
+    }
+
+    #[test]
+    fn test_copy_with_backend_unknown_name_errors() {
+        let result = copy_with_backend("not-a-real-backend", "text");
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("Unknown clipboard backend"));
+    }
+}
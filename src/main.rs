@@ -1,63 +1,16 @@
-use clap::{Arg, App};
-use regex::Regex;
-use std::collections::{HashMap, HashSet};
+use clap::{Arg, App, ValueHint};
+use clap_complete::{generate, Shell};
+use std::collections::HashMap;
+use std::env;
 use std::fs;
-use std::io::Write;
+use std::io::{self, BufRead, Write};
 use std::process::{Command, Stdio};
 use std::error::Error;
 
-/// Copies the provided text to the system clipboard.
-/// Uses platform-specific commands:
-/// - macOS: `pbcopy`
-/// - Windows: `clip`
-/// - Linux: assumes `xclip` is installed.
-fn copy_to_clipboard(text: &str) -> Result<(), Box<dyn Error>> {
-    if cfg!(target_os = "macos") {
-        let mut process = Command::new("pbcopy")
-            .stdin(Stdio::piped())
-            .spawn()?;
-        process.stdin.as_mut().unwrap().write_all(text.as_bytes())?;
-        process.wait()?;
-    } else if cfg!(target_os = "windows") {
-        let mut process = Command::new("clip")
-            .stdin(Stdio::piped())
-            .spawn()?;
-        process.stdin.as_mut().unwrap().write_all(text.as_bytes())?;
-        process.wait()?;
-    } else {
-        // Assume Linux and that xclip is installed.
-        let mut process = Command::new("xclip")
-            .arg("-selection")
-            .arg("clipboard")
-            .stdin(Stdio::piped())
-            .spawn()?;
-        process.stdin.as_mut().unwrap().write_all(text.as_bytes())?;
-        process.wait()?;
-    }
-    Ok(())
-}
-
-/// Extracts variables from the given content.
-/// Variables must be in the format {{variable}}, and are treated case-insensitively.
-fn extract_variables(content: &str) -> HashSet<String> {
-    let re = Regex::new(r"\{\{\s*([a-zA-Z0-9_]+)\s*\}\}").unwrap();
-    let mut found_vars = HashSet::new();
-    for cap in re.captures_iter(content) {
-        found_vars.insert(cap[1].to_lowercase());
-    }
-    found_vars
-}
-
-/// Processes the content by substituting all occurrences of variables with their values.
-/// If a substitution for a variable is missing, the variable remains unchanged.
-fn process_content(content: &str, substitutions: &HashMap<String, String>) -> String {
-    let re = Regex::new(r"\{\{\s*([a-zA-Z0-9_]+)\s*\}\}").unwrap();
-    re.replace_all(content, |caps: &regex::Captures| {
-        let var_name = caps[1].to_lowercase();
-        substitutions.get(&var_name).cloned().unwrap_or_else(|| caps[0].to_string())
-    })
-    .to_string()
-}
+mod clipboard;
+mod template;
+use clipboard::copy_to_clipboard;
+use template::{extract_variables, process_content, Token, Variable};
 
 /// Parses a substitution string of the form key=value.
 /// Returns an error if the format is invalid or if the value is missing.
@@ -74,28 +27,118 @@ fn parse_substitution(sub: &str) -> Result<(String, String), String> {
     Ok((key, value.to_string()))
 }
 
+/// Prompts the user for a value via an external "finder" process, modeled on
+/// navi's `prompt_finder`. The finder binary is `$CBSUB_FINDER` if set,
+/// otherwise `fzf`. `candidates` (from a variable's suggestion command) are
+/// fed to the finder's stdin, and `default` (from a variable's literal
+/// default) pre-fills its query. The variable name is shown as the finder's
+/// prompt header, and the line the user types or selects is read back from
+/// its stdout. When the finder binary can't be spawned (e.g. not installed),
+/// or it returns an empty line, falls back to a plain stdin readline.
+fn prompt_finder(var_name: &str, candidates: &[String], default: Option<&str>) -> Result<String, Box<dyn Error>> {
+    let finder = env::var("CBSUB_FINDER").unwrap_or_else(|_| "fzf".to_string());
+    let header = format!("{}> ", var_name);
+
+    let mut cmd = Command::new(&finder);
+    cmd.arg("--prompt").arg(&header).arg("--print-query");
+    if let Some(default) = default {
+        cmd.arg("--query").arg(default);
+    }
+    let child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(_) => return prompt_stdin(&header, default),
+    };
+
+    {
+        let stdin = child.stdin.as_mut().ok_or("Failed to open finder stdin")?;
+        for candidate in candidates {
+            writeln!(stdin, "{}", candidate)?;
+        }
+    }
+
+    let output = child.wait_with_output()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let chosen = text.lines().last().unwrap_or("").trim();
+
+    if chosen.is_empty() {
+        prompt_stdin(&header, default)
+    } else {
+        Ok(chosen.to_string())
+    }
+}
+
+/// Plain stdin fallback used when no finder binary is available or the
+/// finder returns an empty line. An empty line accepts `default`, if one was
+/// given.
+fn prompt_stdin(header: &str, default: Option<&str>) -> Result<String, Box<dyn Error>> {
+    match default {
+        Some(default) => print!("{}[{}] ", header, default),
+        None => print!("{}", header),
+    }
+    io::stdout().flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    let line = line.trim_end();
+    if line.is_empty() {
+        Ok(default.unwrap_or("").to_string())
+    } else {
+        Ok(line.to_string())
+    }
+}
+
+/// Runs a variable's suggestion command (via `sh -c`) and returns its stdout,
+/// split into candidate lines for the finder.
+fn run_suggestion_command(command: &str) -> Vec<String> {
+    Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .output()
+        .map(|out| String::from_utf8_lossy(&out.stdout).lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
 /// Given a set of variables and an optional positional substitution value,
 /// returns a substitution map if exactly one variable is found when a positional
 /// value is provided. Otherwise, returns an error.
-fn get_single_substitution(variables: &HashSet<String>, pos_value: Option<&str>) -> Result<HashMap<String, String>, String> {
+fn get_single_substitution(variables: &HashMap<String, Variable>, pos_value: Option<&str>) -> Result<HashMap<String, String>, String> {
     let mut subs = HashMap::new();
     if let Some(val) = pos_value {
         if variables.len() != 1 {
             return Err("Error: More than one variable found in the prompt file. Please use the -s flag to specify values for each variable.".to_string());
         }
-        let var = variables.iter().next().unwrap().clone();
+        let var = variables.keys().next().unwrap().clone();
         subs.insert(var, val.to_string());
     }
     Ok(subs)
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let matches = App::new("cbsub")
+/// Looks up a variable's value in the process environment, case-insensitively.
+/// Tries the bare uppercased name first (e.g. `{{home}}` -> `$HOME`), then an
+/// opt-in `CBSUB_`-prefixed form (e.g. `{{home}}` -> `$CBSUB_HOME`).
+fn resolve_env(var_name: &str) -> Option<String> {
+    let upper = var_name.to_uppercase();
+    env::var(&upper)
+        .or_else(|_| env::var(format!("CBSUB_{}", upper)))
+        .ok()
+}
+
+/// Builds the `clap` app definition. Shared between normal argument parsing
+/// and `completions`, which needs a live `App` to hand to `clap_complete`.
+fn build_app() -> App<'static> {
+    App::new("cbsub")
         .version("1.0")
         .about("Substitutes variables in a prompt file and copies the result to the clipboard")
         .arg(Arg::new("file")
              .about("The prompt file to process")
-             .required(true)
+             .value_hint(ValueHint::AnyPath)
+             // Not `required(true)` at the App level: clap fills required
+             // top-level positionals before dispatching a subcommand, which
+             // would make `completions`/`list-vars` unreachable without a
+             // dummy leading file argument. Presence is checked manually in
+             // `main` once we know no subcommand was invoked.
+             .required(false)
              .index(1))
         .arg(Arg::new("value")
              .about("Substitution value for a single variable (when exactly one exists)")
@@ -115,23 +158,145 @@ fn main() -> Result<(), Box<dyn Error>> {
              .short('l')
              .about("List the variables found in the prompt file")
              .takes_value(false))
-        .get_matches();
+        .arg(Arg::new("interactive")
+             .short('i')
+             .long("interactive")
+             .about("Prompt for any variable left unresolved, using an external finder ($CBSUB_FINDER, default fzf)")
+             .takes_value(false))
+        .arg(Arg::new("env")
+             .short('e')
+             .long("env")
+             .about("Resolve any variable still unresolved from the environment (e.g. {{home}} -> $HOME or $CBSUB_HOME)")
+             .takes_value(false))
+        .arg(Arg::new("clipboard")
+             .long("clipboard")
+             .about("Force a specific clipboard backend (arboard, wl-copy, xclip, xsel, pbcopy, clip) instead of auto-detecting. Overrides $CBSUB_CLIPBOARD.")
+             .takes_value(true))
+        .subcommand(App::new("completions")
+             .about("Generates a shell completion script")
+             .arg(Arg::new("shell")
+                  .about("Shell to generate a completion script for")
+                  .possible_values(SHELLS.iter().map(|(name, _)| *name).collect::<Vec<_>>())
+                  .required(true)
+                  .index(1)))
+        .subcommand(App::new("list-vars")
+             .hide(true)
+             .about("Prints the variable names found in FILE, one per line (used by shell completion scripts)")
+             .arg(Arg::new("file")
+                  .value_hint(ValueHint::AnyPath)
+                  .required(true)
+                  .index(1)))
+}
+
+/// The shells `completions` supports, shared by the `possible_values` list on
+/// the `shell` arg and `parse_shell`, so the two can't drift out of sync.
+const SHELLS: &[(&str, Shell)] = &[
+    ("bash", Shell::Bash),
+    ("zsh", Shell::Zsh),
+    ("fish", Shell::Fish),
+    ("powershell", Shell::PowerShell),
+    ("elvish", Shell::Elvish),
+];
+
+/// Parses `shell_name` (as accepted by the `completions` subcommand) into a
+/// `clap_complete::Shell`.
+fn parse_shell(shell_name: &str) -> Result<Shell, Box<dyn Error>> {
+    SHELLS.iter().find(|(name, _)| *name == shell_name).map(|(_, shell)| *shell).ok_or_else(|| {
+        let names: Vec<&str> = SHELLS.iter().map(|(name, _)| *name).collect();
+        format!("Unknown shell '{}'. Expected one of: {}.", shell_name, names.join(", ")).into()
+    })
+}
+
+/// Appends a completer for `-s key=` that shells out to `cbsub list-vars
+/// FILE` to offer the template's own variable names, since the static script
+/// `clap_complete` generates has no way to inspect the named prompt file.
+///
+/// This wraps (rather than replaces) the generated completion function,
+/// which `clap_complete` names `_cbsub` for both bash and zsh: it calls
+/// `_cbsub` first to preserve flag/subcommand/file completion, then adds
+/// variable-name candidates on top when completing a `-s` value.
+fn write_dynamic_var_completion(shell: Shell, out: &mut dyn Write) -> io::Result<()> {
+    match shell {
+        Shell::Bash => writeln!(out, r#"
+_cbsub_with_var_completion() {{
+    _cbsub
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    local prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    local file="${{COMP_WORDS[1]}}"
+    if [[ -f "$file" && ( "$cur" == -s=* || "$prev" == "-s" ) ]]; then
+        COMPREPLY+=( $(compgen -W "$(cbsub list-vars "$file" 2>/dev/null)" -S= -- "${{cur#*=}}") )
+    fi
+}}
+complete -F _cbsub_with_var_completion cbsub"#),
+        Shell::Zsh => writeln!(out, r#"
+_cbsub_with_var_completion() {{
+    _cbsub
+    local file="${{words[2]}}"
+    if [[ -f "$file" && ( "$words[CURRENT]" == -s=* || "$words[CURRENT-1]" == "-s" ) ]]; then
+        compadd -S= -- $(cbsub list-vars "$file" 2>/dev/null)
+    fi
+}}
+compdef _cbsub_with_var_completion cbsub"#),
+        _ => Ok(()),
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut app = build_app();
+    let matches = app.clone().get_matches();
+
+    if let Some(sub_matches) = matches.subcommand_matches("completions") {
+        let shell = parse_shell(sub_matches.value_of("shell").unwrap())?;
+        let mut stdout = io::stdout();
+        generate(shell, &mut app, "cbsub", &mut stdout);
+        write_dynamic_var_completion(shell, &mut stdout)?;
+        return Ok(());
+    }
 
-    let file_path = matches.value_of("file").unwrap();
+    if let Some(sub_matches) = matches.subcommand_matches("list-vars") {
+        let file_path = sub_matches.value_of("file").unwrap();
+        let content = fs::read_to_string(file_path)
+            .map_err(|_| format!("Error: Could not read file '{}'", file_path))?;
+        for name in extract_variables(&content)?.keys() {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+
+    let file_path = match matches.value_of("file") {
+        Some(file_path) => file_path,
+        None => {
+            eprintln!("error: The following required arguments were not provided:\n    <file>");
+            return Err("Missing required argument: file".into());
+        }
+    };
     let content = fs::read_to_string(file_path)
         .map_err(|_| format!("Error: Could not read file '{}'", file_path))?;
 
     // Extract variables from the file.
-    let variables = extract_variables(&content);
+    let variables = extract_variables(&content)?;
 
-    // If -l flag is provided, list the variables and exit.
+    // If -l flag is provided, list each occurrence (with its exact byte
+    // offset in the file) and exit.
     if matches.is_present("list") {
-        if variables.is_empty() {
+        let occurrences: Vec<_> = template::tokenize(&content)?
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Variable { var, start, end } => Some((var, start, end)),
+                Token::Literal { .. } => None,
+            })
+            .collect();
+
+        if occurrences.is_empty() {
             println!("No variables found in the prompt file.");
         } else {
             println!("Found variables:");
-            for var in &variables {
-                println!(" - {{{}}}", var);
+            for (var, start, end) in occurrences {
+                match (&var.default, &var.command) {
+                    (Some(default), _) => println!(" - {{{{{}}}}} (default: {}) at byte {}..{}", var.name, default, start, end),
+                    (None, Some(command)) => println!(" - {{{{{}}}}} (suggestion: {}) at byte {}..{}", var.name, command, start, end),
+                    (None, None) => println!(" - {{{{{}}}}} at byte {}..{}", var.name, start, end),
+                }
             }
         }
         return Ok(());
@@ -161,30 +326,63 @@ fn main() -> Result<(), Box<dyn Error>> {
         substitutions.extend(single_subs);
     }
 
+    // With --env, fall back to the process environment for anything still unresolved.
+    if matches.is_present("env") {
+        for name in variables.keys() {
+            if !substitutions.contains_key(name) {
+                if let Some(value) = resolve_env(name) {
+                    substitutions.insert(name.clone(), value);
+                }
+            }
+        }
+    }
+
+    // In interactive mode, prompt for any variable still unresolved.
+    if matches.is_present("interactive") {
+        for (name, var) in &variables {
+            if !substitutions.contains_key(name) {
+                let candidates = var.command.as_deref().map(run_suggestion_command).unwrap_or_default();
+                let value = prompt_finder(name, &candidates, var.default.as_deref())?;
+                substitutions.insert(name.clone(), value);
+            }
+        }
+    }
+
+    // Pre-fill anything still unresolved that has a literal default, so a
+    // bare invocation (no -s/positional/--env/-i) still substitutes
+    // {{var:default}} references instead of just listing them.
+    for (name, var) in &variables {
+        if !substitutions.contains_key(name) {
+            if let Some(default) = &var.default {
+                substitutions.insert(name.clone(), default.clone());
+            }
+        }
+    }
+
     // If no substitution is provided but variables exist, list them and exit.
     if substitutions.is_empty() {
         if !variables.is_empty() {
             println!("Found variables:");
-            for var in &variables {
-                println!(" - {}", var);
+            for name in variables.keys() {
+                println!(" - {}", name);
             }
             return Ok(());
         } else {
             // No variables found and no substitutions provided; copy file content as-is.
-            copy_to_clipboard(&content)?;
+            copy_to_clipboard(&content, matches.value_of("clipboard"))?;
             println!("File content copied to clipboard.");
             return Ok(());
         }
     }
 
     // Perform substitutions.
-    let result = process_content(&content, &substitutions);
+    let result = process_content(&content, &substitutions)?;
 
     // If the preview flag (-p) is set, display the result instead of copying.
     if matches.is_present("preview") {
         println!("{}", result);
     } else {
-        copy_to_clipboard(&result)?;
+        copy_to_clipboard(&result, matches.value_of("clipboard"))?;
         println!("Processed content copied to clipboard.");
     }
 
@@ -194,53 +392,9 @@ fn main() -> Result<(), Box<dyn Error>> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::collections::HashMap;
-
-    #[test]
-    fn test_extract_variables_empty() {
-        let content = "This is a test with no variables.";
-        let vars = extract_variables(content);
-        assert!(vars.is_empty());
-    }
-
-    #[test]
-    fn test_extract_variables() {
-        let content = "Hello {{name}}, your code is {{code}}. Again, hi {{name}}!";
-        let vars = extract_variables(content);
-        assert_eq!(vars.len(), 2);
-        assert!(vars.contains("name"));
-        assert!(vars.contains("code"));
-    }
-
-    #[test]
-    fn test_process_content_complete() {
-        let content = "Hello {{name}}, your code is {{code}}.";
-        let mut subs = HashMap::new();
-        subs.insert("name".to_string(), "Alice".to_string());
-        subs.insert("code".to_string(), "9876".to_string());
-        let result = process_content(content, &subs);
-        assert_eq!(result, "Hello Alice, your code is 9876.");
-    }
-
-    #[test]
-    fn test_process_content_partial() {
-        let content = "Hello {{name}}, your code is {{code}}.";
-        let mut subs = HashMap::new();
-        subs.insert("name".to_string(), "Alice".to_string());
-        // No substitution for "code": it should remain unchanged.
-        let result = process_content(content, &subs);
-        assert_eq!(result, "Hello Alice, your code is {{code}}.");
-    }
 
-    #[test]
-    fn test_case_insensitivity() {
-        let content = "Hello {{Name}}, your code is {{CoDe}}.";
-        let mut subs = HashMap::new();
-        subs.insert("name".to_string(), "Alice".to_string());
-        subs.insert("code".to_string(), "9876".to_string());
-        let result = process_content(content, &subs);
-        assert_eq!(result, "Hello Alice, your code is 9876.");
-    }
+    // `extract_variables`/`process_content` parsing behavior is covered by
+    // `template`'s own tests; these cover the functions that live in `main`.
 
     #[test]
     fn test_parse_substitution_valid() {
@@ -270,7 +424,7 @@ mod tests {
     #[test]
     fn test_get_single_substitution_success() {
         let content = "Hello {{name}}!";
-        let vars = extract_variables(content);
+        let vars = extract_variables(content).unwrap();
         let result = get_single_substitution(&vars, Some("Alice"));
         assert!(result.is_ok());
         let subs = result.unwrap();
@@ -280,9 +434,49 @@ mod tests {
     #[test]
     fn test_get_single_substitution_failure() {
         let content = "Hello {{name}} and {{code}}!";
-        let vars = extract_variables(content);
+        let vars = extract_variables(content).unwrap();
         let result = get_single_substitution(&vars, Some("Alice"));
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), "Error: More than one variable found in the prompt file. Please use the -s flag to specify values for each variable.");
     }
+
+    #[test]
+    fn test_resolve_env_bare_name() {
+        env::set_var("CBSUB_TEST_BARE", "from-bare");
+        assert_eq!(resolve_env("cbsub_test_bare"), Some("from-bare".to_string()));
+        env::remove_var("CBSUB_TEST_BARE");
+    }
+
+    #[test]
+    fn test_resolve_env_prefixed_fallback() {
+        env::remove_var("TEST_PREFIXED_ONLY");
+        env::set_var("CBSUB_TEST_PREFIXED_ONLY", "from-prefix");
+        assert_eq!(resolve_env("test_prefixed_only"), Some("from-prefix".to_string()));
+        env::remove_var("CBSUB_TEST_PREFIXED_ONLY");
+    }
+
+    #[test]
+    fn test_resolve_env_missing() {
+        env::remove_var("CBSUB_TEST_MISSING_VAR");
+        assert_eq!(resolve_env("cbsub_test_missing_var"), None);
+    }
+
+    #[test]
+    fn test_completions_subcommand_reachable_without_file_arg() {
+        let matches = build_app().try_get_matches_from(vec!["cbsub", "completions", "bash"]);
+        assert!(matches.is_ok());
+        let matches = matches.unwrap();
+        assert!(matches.subcommand_matches("completions").is_some());
+        assert!(matches.value_of("file").is_none());
+    }
+
+    #[test]
+    fn test_list_vars_subcommand_reachable_without_file_arg() {
+        let matches = build_app().try_get_matches_from(vec!["cbsub", "list-vars", "prompt.txt"]);
+        assert!(matches.is_ok());
+        let matches = matches.unwrap();
+        let sub = matches.subcommand_matches("list-vars").unwrap();
+        assert_eq!(sub.value_of("file"), Some("prompt.txt"));
+        assert!(matches.value_of("file").is_none());
+    }
 }
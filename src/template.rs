@@ -0,0 +1,262 @@
+//! Template parsing and substitution.
+//!
+//! Content is tokenized into literal spans and `{{variable}}` references
+//! (using `nom`, in the spirit of pigweed's `StringSub` interpolator) rather
+//! than matched with a single regex. This lets literal `{{` be escaped as
+//! `{{{{`, and lets a substituted value itself contain further `{{var}}`
+//! references, which are expanded in turn.
+
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_until, take_while1},
+    character::complete::{char, multispace0},
+    combinator::{map, opt},
+    sequence::{delimited, preceded, tuple},
+    IResult,
+};
+use std::collections::{HashMap, HashSet};
+
+/// A variable reference parsed from `{{name}}`, `{{name:default}}`, or
+/// `{{name: shell command}}`. The optional `:` suffix is a shell command that
+/// generates candidate values (used by `-i`/`--interactive`) when whitespace
+/// directly follows the `:`, and a literal default otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    pub default: Option<String>,
+    pub command: Option<String>,
+}
+
+/// A span of template content: either literal text to copy verbatim, or a
+/// variable reference to substitute. `start`/`end` are byte offsets into the
+/// original content, so callers (e.g. `-l`) can report exactly where each
+/// occurrence was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Literal { text: String, start: usize, end: usize },
+    Variable { var: Variable, start: usize, end: usize },
+}
+
+/// Splits a variable's optional `:` suffix (the raw text between `:` and
+/// `}}`, not yet trimmed) into a literal default or a suggestion command. A
+/// suffix starting with whitespace right after the `:` (e.g.
+/// `{{branch: git branch ...}}`) is a command; anything else non-empty (e.g.
+/// `{{branch:main}}`, or even `{{branch:machine learning}}`) is a literal
+/// default, trimmed of the surrounding whitespace `take_until("}}")` leaves in.
+fn classify_suffix(raw_suffix: Option<&str>) -> (Option<String>, Option<String>) {
+    match raw_suffix {
+        Some(s) if s.starts_with(char::is_whitespace) => {
+            let trimmed = s.trim();
+            if trimmed.is_empty() { (None, None) } else { (None, Some(trimmed.to_string())) }
+        }
+        Some(s) => {
+            let trimmed = s.trim();
+            if trimmed.is_empty() { (None, None) } else { (Some(trimmed.to_string()), None) }
+        }
+        None => (None, None),
+    }
+}
+
+/// Matches a literal `{{{{`, which escapes to a literal `{{` in the output.
+fn parse_escape(input: &str) -> IResult<&str, ()> {
+    map(tag("{{{{"), |_| ())(input)
+}
+
+/// Matches a `{{name}}`, `{{name:default}}`, or `{{name: command}}` reference.
+fn parse_variable(input: &str) -> IResult<&str, Variable> {
+    delimited(
+        tag("{{"),
+        map(
+            tuple((
+                multispace0,
+                take_while1(|c: char| c.is_alphanumeric() || c == '_'),
+                multispace0,
+                opt(preceded(char(':'), take_until("}}"))),
+            )),
+            |(_, name, _, suffix): (&str, &str, &str, Option<&str>)| {
+                let (default, command) = classify_suffix(suffix);
+                Variable { name: name.to_lowercase(), default, command }
+            },
+        ),
+        tag("}}"),
+    )(input)
+}
+
+/// Tokenizes `content` into literal spans and variable references, honoring
+/// the `{{{{` escape. Byte offsets are tracked manually (rather than inside
+/// the nom combinators) so each `Token` carries its exact position.
+pub fn tokenize(content: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = content;
+    let mut offset = 0usize;
+
+    while !rest.is_empty() {
+        if let Ok((next, ())) = alt((parse_escape,))(rest) {
+            let consumed = rest.len() - next.len();
+            tokens.push(Token::Literal { text: "{{".to_string(), start: offset, end: offset + consumed });
+            offset += consumed;
+            rest = next;
+            continue;
+        }
+
+        if let Ok((next, var)) = parse_variable(rest) {
+            let consumed = rest.len() - next.len();
+            tokens.push(Token::Variable { var, start: offset, end: offset + consumed });
+            offset += consumed;
+            rest = next;
+            continue;
+        }
+
+        // Not an escape or a variable at this position: consume a literal
+        // run up to the next `{{` (or the rest of the content). Search via
+        // `match_indices` (not a fixed-byte-offset slice) so this stays safe
+        // when `rest` starts with a multi-byte character; any match it finds
+        // is guaranteed to land on a char boundary, since `{` can't occur as
+        // a UTF-8 continuation byte.
+        let next_marker = rest.match_indices("{{").map(|(i, _)| i).find(|&i| i > 0).unwrap_or(rest.len());
+        let (text, next) = rest.split_at(next_marker);
+        tokens.push(Token::Literal { text: text.to_string(), start: offset, end: offset + text.len() });
+        offset += text.len();
+        rest = next;
+    }
+
+    Ok(tokens)
+}
+
+/// Extracts the distinct variables referenced in `content`, keyed by
+/// (lowercased) name. When a variable is referenced more than once, the
+/// first occurrence's default/command wins.
+pub fn extract_variables(content: &str) -> Result<HashMap<String, Variable>, String> {
+    let mut vars = HashMap::new();
+    for token in tokenize(content)? {
+        if let Token::Variable { var, .. } = token {
+            vars.entry(var.name.clone()).or_insert(var);
+        }
+    }
+    Ok(vars)
+}
+
+/// Substitutes all variable references in `content`, expanding recursively
+/// when a substituted value itself contains `{{var}}` references. Falls back
+/// to a variable's literal default, then leaves the reference unchanged, when
+/// no substitution is given. Errors if expansion cycles back to a variable
+/// that is already being expanded.
+pub fn process_content(content: &str, substitutions: &HashMap<String, String>) -> Result<String, String> {
+    expand(content, substitutions, &mut HashSet::new())
+}
+
+fn expand(content: &str, substitutions: &HashMap<String, String>, in_progress: &mut HashSet<String>) -> Result<String, String> {
+    let mut output = String::new();
+    for token in tokenize(content)? {
+        match token {
+            Token::Literal { text, .. } => output.push_str(&text),
+            Token::Variable { var, start, end } => {
+                if let Some(value) = substitutions.get(&var.name) {
+                    if !in_progress.insert(var.name.clone()) {
+                        return Err(format!("Cycle detected while expanding variable '{{{{{}}}}}'", var.name));
+                    }
+                    let expanded = expand(value, substitutions, in_progress)?;
+                    in_progress.remove(&var.name);
+                    output.push_str(&expanded);
+                } else if let Some(default) = &var.default {
+                    output.push_str(default);
+                } else {
+                    output.push_str(&content[start..end]);
+                }
+            }
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_literal_and_variable() {
+        let tokens = tokenize("Hello {{name}}!").unwrap();
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[0], Token::Literal { text: "Hello ".to_string(), start: 0, end: 6 });
+        match &tokens[1] {
+            Token::Variable { var, start, end } => {
+                assert_eq!(var.name, "name");
+                assert_eq!((*start, *end), (6, 14));
+            }
+            other => panic!("expected a variable token, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_tokenize_literal_with_multibyte_prefix() {
+        let tokens = tokenize("日本語 {{name}}!").unwrap();
+        assert_eq!(tokens.len(), 3);
+        match &tokens[1] {
+            Token::Variable { var, .. } => assert_eq!(var.name, "name"),
+            other => panic!("expected a variable token, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_tokenize_escape() {
+        let tokens = tokenize("literal {{{{ brace").unwrap();
+        assert!(tokens.iter().any(|t| matches!(t, Token::Literal { text, .. } if text == "{{")));
+    }
+
+    #[test]
+    fn test_extract_variables_dedup() {
+        let vars = extract_variables("{{name}} and {{name}} again").unwrap();
+        assert_eq!(vars.len(), 1);
+        assert!(vars.contains_key("name"));
+    }
+
+    #[test]
+    fn test_extract_variables_default_containing_a_space_stays_a_default() {
+        // No whitespace directly after the `:` means this is a literal
+        // default, even though the default's value itself contains a space.
+        let vars = extract_variables("{{topic:machine learning}}").unwrap();
+        let var = vars.get("topic").unwrap();
+        assert_eq!(var.default, Some("machine learning".to_string()));
+        assert_eq!(var.command, None);
+    }
+
+    #[test]
+    fn test_extract_variables_suggestion_command_needs_leading_space() {
+        // Whitespace right after the `:` signals a suggestion command.
+        let vars = extract_variables("{{branch: git branch --format='%(refname:short)'}}").unwrap();
+        let var = vars.get("branch").unwrap();
+        assert_eq!(var.default, None);
+        assert_eq!(var.command.as_deref(), Some("git branch --format='%(refname:short)'"));
+    }
+
+    #[test]
+    fn test_process_content_applies_default_with_no_substitutions_given() {
+        let result = process_content("Checkout {{branch:main}}.", &HashMap::new()).unwrap();
+        assert_eq!(result, "Checkout main.");
+    }
+
+    #[test]
+    fn test_process_content_recursive_substitution() {
+        let mut subs = HashMap::new();
+        subs.insert("greeting".to_string(), "Hello {{name}}".to_string());
+        subs.insert("name".to_string(), "Alice".to_string());
+        let result = process_content("{{greeting}}!", &subs).unwrap();
+        assert_eq!(result, "Hello Alice!");
+    }
+
+    #[test]
+    fn test_process_content_detects_cycle() {
+        let mut subs = HashMap::new();
+        subs.insert("a".to_string(), "{{b}}".to_string());
+        subs.insert("b".to_string(), "{{a}}".to_string());
+        let result = process_content("{{a}}", &subs);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_process_content_escape() {
+        let subs = HashMap::new();
+        let result = process_content("literal {{{{ brace", &subs).unwrap();
+        assert_eq!(result, "literal {{ brace");
+    }
+}